@@ -0,0 +1,377 @@
+use crate::{cluster::rpc_client::RpcClient, error::Error, protos, Server, ServerId, ServerKind};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+tokio::task_local! {
+    static LATENCY_START: std::cell::Cell<Option<std::time::Instant>>;
+}
+
+/// Hook for cross-cutting behavior around an `RpcClient::call`, e.g. tracing,
+/// auth, or retries. Hooks are run in chain order before the call and in
+/// reverse order after it, mirroring how middleware stacks usually compose.
+#[async_trait]
+pub trait Interceptor: Send + Sync {
+    async fn before_call(&self, target: &Server, req: &mut protos::Request);
+    async fn after_call(&self, target: &Server, result: &mut Result<protos::Response, Error>);
+
+    /// How many additional attempts this interceptor allows after a
+    /// transient failure. `InterceptedClient` takes the max across the
+    /// whole chain; most interceptors have nothing to say here.
+    fn max_retries(&self) -> u32 {
+        0
+    }
+}
+
+/// An `RpcClient` adapter that runs an `Interceptor` chain around any other
+/// `RpcClient` implementation, without having to fork `NatsClient` itself.
+pub struct InterceptedClient {
+    inner: Arc<dyn RpcClient + Send + Sync>,
+    chain: Vec<Arc<dyn Interceptor>>,
+}
+
+impl InterceptedClient {
+    pub fn new(inner: Arc<dyn RpcClient + Send + Sync>, chain: Vec<Arc<dyn Interceptor>>) -> Self {
+        Self { inner, chain }
+    }
+
+    /// Runs the chain's `before_call` hooks against `req` in place, then
+    /// delegates to `inner.call` and runs `after_call` in reverse. Takes
+    /// `req` by `&mut` rather than cloning it internally so that retries
+    /// (see `call`) reuse the same mutated request instead of re-deriving
+    /// interceptor state, e.g. a trace id, from scratch on every attempt.
+    async fn call_once(
+        &self,
+        target: &Arc<Server>,
+        req: &mut protos::Request,
+    ) -> Result<protos::Response, Error> {
+        for interceptor in &self.chain {
+            interceptor.before_call(target, req).await;
+        }
+
+        let mut result = self.inner.call(target.clone(), req.clone()).await;
+
+        for interceptor in self.chain.iter().rev() {
+            interceptor.after_call(target, &mut result).await;
+        }
+
+        result
+    }
+}
+
+#[async_trait]
+impl RpcClient for InterceptedClient {
+    async fn call(
+        &self,
+        target: Arc<Server>,
+        req: protos::Request,
+    ) -> Result<protos::Response, Error> {
+        let max_retries = self
+            .chain
+            .iter()
+            .map(|i| i.max_retries())
+            .max()
+            .unwrap_or(0);
+
+        let mut req = req;
+        LATENCY_START
+            .scope(std::cell::Cell::new(None), async {
+                let mut attempt = 0;
+                loop {
+                    // Reuse the same `req` across attempts: interceptor
+                    // mutations like the trace id injected by
+                    // `TraceIdInterceptor` must stay stable for the whole
+                    // retry sequence, not get regenerated every attempt.
+                    let result = self.call_once(&target, &mut req).await;
+
+                    let is_timeout =
+                        matches!(&result, Err(Error::Nats(e)) if e.kind() == std::io::ErrorKind::TimedOut);
+                    if is_timeout && attempt < max_retries {
+                        attempt += 1;
+                        continue;
+                    }
+
+                    return result;
+                }
+            })
+            .await
+    }
+
+    async fn kick_user(
+        &self,
+        server_id: ServerId,
+        server_kind: ServerKind,
+        kick_msg: protos::KickMsg,
+    ) -> Result<protos::KickAnswer, Error> {
+        // kick_user/push_to_user have no `Server` target to run before/after
+        // hooks against, so they just delegate straight to the inner client.
+        self.inner.kick_user(server_id, server_kind, kick_msg).await
+    }
+
+    async fn push_to_user(
+        &self,
+        server_id: &ServerId,
+        server_kind: &ServerKind,
+        push_msg: protos::Push,
+    ) -> Result<(), Error> {
+        self.inner
+            .push_to_user(server_id, server_kind, push_msg)
+            .await
+    }
+}
+
+fn next_trace_id() -> String {
+    static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    let id = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    format!("{:x}-{:x}", std::process::id(), id)
+}
+
+/// Injects a trace id into `Request.metadata` if one isn't already present,
+/// so it propagates through logs on both sides of the call.
+pub struct TraceIdInterceptor;
+
+#[async_trait]
+impl Interceptor for TraceIdInterceptor {
+    async fn before_call(&self, _target: &Server, req: &mut protos::Request) {
+        let mut metadata: serde_json::Map<String, serde_json::Value> = if req.metadata.is_empty() {
+            serde_json::Map::new()
+        } else {
+            serde_json::from_slice(&req.metadata).unwrap_or_default()
+        };
+
+        metadata
+            .entry("trace_id".to_owned())
+            .or_insert_with(|| serde_json::Value::String(next_trace_id()));
+
+        if let Ok(encoded) = serde_json::to_vec(&metadata) {
+            req.metadata = encoded;
+        }
+    }
+
+    async fn after_call(&self, _target: &Server, _result: &mut Result<protos::Response, Error>) {}
+}
+
+/// Records how long each call took via `record_histogram_duration`.
+pub struct LatencyInterceptor {
+    logger: slog::Logger,
+    reporter: crate::metrics::ThreadSafeReporter,
+}
+
+impl LatencyInterceptor {
+    pub fn new(logger: slog::Logger, reporter: crate::metrics::ThreadSafeReporter) -> Self {
+        Self { logger, reporter }
+    }
+}
+
+#[async_trait]
+impl Interceptor for LatencyInterceptor {
+    async fn before_call(&self, _target: &Server, _req: &mut protos::Request) {
+        let _ = LATENCY_START.try_with(|cell| cell.set(Some(std::time::Instant::now())));
+    }
+
+    async fn after_call(&self, _target: &Server, _result: &mut Result<protos::Response, Error>) {
+        if let Ok(Some(start)) = LATENCY_START.try_with(|cell| cell.get()) {
+            crate::metrics::record_histogram_duration(
+                self.logger.clone(),
+                self.reporter.clone(),
+                "rpc_call_duration_seconds",
+                start,
+                &[],
+            )
+            .await;
+        }
+    }
+}
+
+/// Allows idempotent calls to be retried on `Error::Nats` timeouts. Carries
+/// no state of its own: `InterceptedClient::call` owns the retry loop and
+/// simply asks every interceptor in the chain how many attempts it permits.
+pub struct RetryInterceptor {
+    max_retries: u32,
+}
+
+impl RetryInterceptor {
+    pub fn new(max_retries: u32) -> Self {
+        Self { max_retries }
+    }
+}
+
+#[async_trait]
+impl Interceptor for RetryInterceptor {
+    async fn before_call(&self, _target: &Server, _req: &mut protos::Request) {}
+    async fn after_call(&self, _target: &Server, _result: &mut Result<protos::Response, Error>) {}
+
+    fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::rpc_client::nats_timeout_error;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// An `RpcClient` stub that fails the first `fail_times` calls with a
+    /// NATS timeout and records the trace id on every call it sees, so
+    /// interceptor-chain tests can assert on both retry counts and which
+    /// request fields survived across retries.
+    struct CountingClient {
+        calls: std::sync::atomic::AtomicU32,
+        fail_times: u32,
+        seen_trace_ids: Mutex<Vec<Option<String>>>,
+    }
+
+    impl CountingClient {
+        fn new(fail_times: u32) -> Self {
+            Self {
+                calls: std::sync::atomic::AtomicU32::new(0),
+                fail_times,
+                seen_trace_ids: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn trace_id(req: &protos::Request) -> Option<String> {
+            let metadata: serde_json::Map<String, serde_json::Value> =
+                serde_json::from_slice(&req.metadata).ok()?;
+            metadata.get("trace_id")?.as_str().map(str::to_owned)
+        }
+    }
+
+    #[async_trait]
+    impl RpcClient for CountingClient {
+        async fn call(
+            &self,
+            _target: Arc<Server>,
+            req: protos::Request,
+        ) -> Result<protos::Response, Error> {
+            self.seen_trace_ids
+                .lock()
+                .unwrap()
+                .push(Self::trace_id(&req));
+            let call_idx = self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if call_idx < self.fail_times {
+                return Err(Error::Nats(nats_timeout_error()));
+            }
+            Ok(protos::Response::default())
+        }
+
+        async fn kick_user(
+            &self,
+            _server_id: ServerId,
+            _server_kind: ServerKind,
+            _kick_msg: protos::KickMsg,
+        ) -> Result<protos::KickAnswer, Error> {
+            unimplemented!("not exercised by interceptor tests")
+        }
+
+        async fn push_to_user(
+            &self,
+            _server_id: &ServerId,
+            _server_kind: &ServerKind,
+            _push_msg: protos::Push,
+        ) -> Result<(), Error> {
+            unimplemented!("not exercised by interceptor tests")
+        }
+    }
+
+    /// Records `"{label}:before"`/`"{label}:after"` so tests can assert the
+    /// chain runs before-hooks in order and after-hooks in reverse order.
+    struct OrderRecordingInterceptor {
+        label: &'static str,
+        order: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Interceptor for OrderRecordingInterceptor {
+        async fn before_call(&self, _target: &Server, _req: &mut protos::Request) {
+            self.order
+                .lock()
+                .unwrap()
+                .push(format!("{}:before", self.label));
+        }
+
+        async fn after_call(
+            &self,
+            _target: &Server,
+            _result: &mut Result<protos::Response, Error>,
+        ) {
+            self.order
+                .lock()
+                .unwrap()
+                .push(format!("{}:after", self.label));
+        }
+    }
+
+    fn test_target() -> Arc<Server> {
+        Arc::new(Server {
+            id: ServerId::from("my_id"),
+            kind: ServerKind::from("metagame"),
+            metadata: HashMap::new(),
+            hostname: "hostname".to_owned(),
+            frontend: false,
+        })
+    }
+
+    #[tokio::test]
+    async fn intercepted_client_runs_chain_before_in_order_and_after_in_reverse() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let chain: Vec<Arc<dyn Interceptor>> = vec![
+            Arc::new(OrderRecordingInterceptor {
+                label: "a",
+                order: order.clone(),
+            }),
+            Arc::new(OrderRecordingInterceptor {
+                label: "b",
+                order: order.clone(),
+            }),
+        ];
+        let client = InterceptedClient::new(Arc::new(CountingClient::new(0)), chain);
+
+        client
+            .call(test_target(), protos::Request::default())
+            .await
+            .expect("call should succeed");
+
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec!["a:before", "b:before", "b:after", "a:after"],
+        );
+    }
+
+    #[tokio::test]
+    async fn intercepted_client_retries_timeouts_and_keeps_the_same_trace_id() {
+        let inner = Arc::new(CountingClient::new(2));
+        let chain: Vec<Arc<dyn Interceptor>> = vec![
+            Arc::new(TraceIdInterceptor),
+            Arc::new(RetryInterceptor::new(2)),
+        ];
+        let client = InterceptedClient::new(inner.clone(), chain);
+
+        let response = client.call(test_target(), protos::Request::default()).await;
+
+        assert!(response.is_ok());
+
+        let seen = inner.seen_trace_ids.lock().unwrap();
+        assert_eq!(seen.len(), 3, "expected the initial attempt plus 2 retries");
+        assert!(seen.iter().all(|id| id.is_some()));
+        assert!(
+            seen.windows(2).all(|w| w[0] == w[1]),
+            "trace id must stay stable across retries, got {:?}",
+            seen
+        );
+    }
+
+    #[tokio::test]
+    async fn intercepted_client_gives_up_after_max_retries() {
+        let inner = Arc::new(CountingClient::new(u32::MAX));
+        let chain: Vec<Arc<dyn Interceptor>> = vec![Arc::new(RetryInterceptor::new(2))];
+        let client = InterceptedClient::new(inner, chain);
+
+        let response = client.call(test_target(), protos::Request::default()).await;
+
+        assert!(
+            matches!(response, Err(Error::Nats(e)) if e.kind() == std::io::ErrorKind::TimedOut)
+        );
+    }
+}
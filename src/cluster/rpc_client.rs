@@ -1,9 +1,17 @@
-use crate::{error::Error, protos, utils, Server, ServerId, ServerKind};
+use crate::{
+    error::Error,
+    protos,
+    shutdown::{race_with_trip_wire, TripWire},
+    utils, Server, ServerId, ServerKind,
+};
 use async_trait::async_trait;
+use futures::StreamExt;
 use prost::Message;
 use slog::trace;
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
+use tokio::sync::{oneshot, watch, Mutex};
 
 #[async_trait]
 pub trait RpcClient {
@@ -18,7 +26,7 @@ pub trait RpcClient {
         server_kind: ServerKind,
         kick_msg: protos::KickMsg,
     ) -> Result<protos::KickAnswer, Error>;
-    fn push_to_user(
+    async fn push_to_user(
         &self,
         server_id: &ServerId,
         server_kind: &ServerKind,
@@ -26,12 +34,57 @@ pub trait RpcClient {
     ) -> Result<(), Error>;
 }
 
+/// The storage backend used for the JetStream push stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageType {
+    File,
+    Memory,
+}
+
+/// Controls how `push_to_user` delivers messages to frontends.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PushDelivery {
+    /// Fire-and-forget delivery over core NATS. Pushes to a user with no
+    /// active subscriber are silently dropped.
+    CoreNats,
+    /// At-least-once delivery backed by a JetStream stream. Pushes are
+    /// persisted for `ttl` so a reconnecting user can replay what it missed.
+    JetStream {
+        ttl: Duration,
+        storage: StorageType,
+        max_msgs_per_subject: i64,
+    },
+}
+
+impl Default for PushDelivery {
+    fn default() -> Self {
+        PushDelivery::CoreNats
+    }
+}
+
+/// Name of the JetStream stream that captures the `user_messages_topic`
+/// subject space, i.e. every subject `push_to_user` can publish to.
+const PUSH_STREAM_NAME: &str = "pitaya-pushes";
+const PUSH_STREAM_SUBJECTS: &str = "pitaya-push.>";
+
+/// Starting delay for the reconnection backoff. Doubles after every failed
+/// attempt, capped at `MAX_RECONNECTION_BACKOFF`.
+const INITIAL_RECONNECTION_BACKOFF: Duration = Duration::from_millis(200);
+const MAX_RECONNECTION_BACKOFF: Duration = Duration::from_secs(30);
+
 pub struct Config {
     pub address: String,
     pub connection_timeout: Duration,
     pub request_timeout: Duration,
     pub max_reconnection_attempts: u32,
     pub max_pending_messages: u32,
+    pub push_delivery: PushDelivery,
+    /// How long `replay_pending_pushes` waits for the next backlog message
+    /// before deciding the caller is caught up. Kept separate from
+    /// `request_timeout`, which bounds a single RPC: sharing one knob would
+    /// mean tuning `request_timeout` down for snappier RPC failure
+    /// detection silently truncates replay of the JetStream backlog too.
+    pub replay_idle_timeout: Duration,
 }
 
 impl Default for Config {
@@ -42,36 +95,459 @@ impl Default for Config {
             request_timeout: Duration::from_secs(10),
             max_reconnection_attempts: 5,
             max_pending_messages: 100,
+            push_delivery: PushDelivery::CoreNats,
+            replay_idle_timeout: Duration::from_secs(2),
         }
     }
 }
 
+/// Connection-state transitions that `NatsClient` goes through. Consumers can
+/// watch these via `NatsClient::subscribe_state` to react to outages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Disconnected,
+    Connected,
+    Paused,
+}
+
+enum PendingOp {
+    Call {
+        target: Arc<Server>,
+        req: protos::Request,
+        reply: oneshot::Sender<Result<protos::Response, Error>>,
+    },
+    KickUser {
+        server_kind: ServerKind,
+        kick_msg: protos::KickMsg,
+        reply: oneshot::Sender<Result<protos::KickAnswer, Error>>,
+    },
+    Push {
+        server_kind: ServerKind,
+        push_msg: protos::Push,
+        reply: oneshot::Sender<Result<(), Error>>,
+    },
+}
+
+/// The parts of `NatsClient` that every clone must observe consistently.
+/// Plain (non-`Arc`-shared) fields here would let a "management" clone's
+/// `connect`/`pause`/`resume`/`close` silently diverge from a "calling"
+/// clone's view of the world, leaving the calling clone stuck buffering
+/// against a connection that, from its perspective, never comes back.
+struct Shared {
+    connection: Option<async_nats::Client>,
+    jetstream: Option<async_nats::jetstream::Context>,
+    draining: Option<TripWire>,
+    trip_wire: Option<TripWire>,
+}
+
 #[derive(Clone)]
 pub struct NatsClient {
     config: Arc<Config>,
-    connection: Option<nats::Connection>,
+    shared: Arc<RwLock<Shared>>,
+    pending: Arc<Mutex<VecDeque<PendingOp>>>,
+    state_tx: Arc<watch::Sender<ConnectionState>>,
+    state_rx: watch::Receiver<ConnectionState>,
     logger: slog::Logger,
 }
 
 impl NatsClient {
     pub fn new(logger: slog::Logger, config: Arc<Config>) -> Self {
+        let (state_tx, state_rx) = watch::channel(ConnectionState::Disconnected);
         Self {
             config,
-            connection: None,
+            shared: Arc::new(RwLock::new(Shared {
+                connection: None,
+                jetstream: None,
+                draining: None,
+                trip_wire: None,
+            })),
+            pending: Arc::new(Mutex::new(VecDeque::new())),
+            state_tx: Arc::new(state_tx),
+            state_rx,
             logger,
         }
     }
 
-    pub fn connect(&mut self) -> Result<(), Error> {
-        assert!(self.connection.is_none());
-        let nc = nats::connect(&self.config.address).map_err(|e| Error::Nats(e))?;
-        self.connection = Some(nc);
+    /// Wires a draining `TripWire` into the client so new calls fail fast
+    /// with `Error::ShuttingDown` as soon as shutdown starts, instead of
+    /// racing a connection that's about to be torn down.
+    pub fn with_draining(self, draining: TripWire) -> Self {
+        self.shared.write().unwrap().draining = Some(draining);
+        self
+    }
+
+    /// Wires a force-cancel `TripWire` into the client so in-flight calls
+    /// can be cancelled once their shutdown grace period has elapsed,
+    /// instead of hanging until `request_timeout`.
+    pub fn with_trip_wire(self, trip_wire: TripWire) -> Self {
+        self.shared.write().unwrap().trip_wire = Some(trip_wire);
+        self
+    }
+
+    fn check_not_shutting_down(&self) -> Result<(), Error> {
+        if matches!(&self.shared.read().unwrap().draining, Some(draining) if draining.is_tripped())
+        {
+            return Err(Error::ShuttingDown);
+        }
         Ok(())
     }
 
-    pub fn close(&mut self) {
-        if let Some(conn) = self.connection.take() {
-            conn.close();
+    fn connection(&self) -> Option<async_nats::Client> {
+        self.shared.read().unwrap().connection.clone()
+    }
+
+    fn jetstream(&self) -> Option<async_nats::jetstream::Context> {
+        self.shared.read().unwrap().jetstream.clone()
+    }
+
+    fn trip_wire(&self) -> Option<TripWire> {
+        self.shared.read().unwrap().trip_wire.clone()
+    }
+
+    /// Rejects anything still buffered (we're shutting down, not waiting
+    /// for a reconnect) and then closes the connection.
+    pub async fn drain_and_close(&self) {
+        self.reject_pending(|| Error::ShuttingDown).await;
+        self.close();
+    }
+
+    /// Fails every buffered operation with `make_err()` instead of leaving
+    /// it to await a `oneshot` reply that will never come.
+    async fn reject_pending(&self, make_err: impl Fn() -> Error) {
+        let ops: Vec<PendingOp> = {
+            let mut pending = self.pending.lock().await;
+            pending.drain(..).collect()
+        };
+        for op in ops {
+            match op {
+                PendingOp::Call { reply, .. } => {
+                    let _ = reply.send(Err(make_err()));
+                }
+                PendingOp::KickUser { reply, .. } => {
+                    let _ = reply.send(Err(make_err()));
+                }
+                PendingOp::Push { reply, .. } => {
+                    let _ = reply.send(Err(make_err()));
+                }
+            }
+        }
+    }
+
+    /// Watch connection-state transitions (connected/disconnected/paused).
+    pub fn subscribe_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state_rx.clone()
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        *self.state_rx.borrow()
+    }
+
+    pub async fn connect(&self) -> Result<(), Error> {
+        assert!(self.connection().is_none());
+        self.establish_connection().await
+    }
+
+    /// Detaches from the NATS mesh without giving up on the connection
+    /// permanently. Outbound operations are buffered until `resume` is
+    /// called, up to `max_pending_messages`.
+    pub fn pause(&self) {
+        let mut shared = self.shared.write().unwrap();
+        shared.jetstream.take();
+        shared.connection.take();
+        drop(shared);
+        let _ = self.state_tx.send(ConnectionState::Paused);
+    }
+
+    /// Re-attaches to the NATS mesh after a `pause`, retrying with the same
+    /// backoff policy as `connect`, and replays anything buffered meanwhile.
+    pub async fn resume(&self) -> Result<(), Error> {
+        assert!(self.connection().is_none());
+        self.establish_connection().await
+    }
+
+    pub fn close(&self) {
+        // async_nats::Client flushes and disconnects when its last handle is
+        // dropped, so taking it out of the option is all we need to do here.
+        let mut shared = self.shared.write().unwrap();
+        shared.jetstream.take();
+        shared.connection.take();
+        drop(shared);
+        let _ = self.state_tx.send(ConnectionState::Disconnected);
+    }
+
+    /// Runs the reconnection backoff loop until it either connects or gives
+    /// up after `max_reconnection_attempts`. Called both from `connect`/
+    /// `resume` and, on a mid-session drop, from the `event_callback`
+    /// installed by `try_connect_once` below, so a dropped connection goes
+    /// through the exact same backoff/max-attempts/buffering path as the
+    /// initial connect instead of leaning on async-nats's own (unconfigured,
+    /// unbounded) reconnection logic.
+    async fn establish_connection(&self) -> Result<(), Error> {
+        let mut attempt = 0;
+        let mut backoff = INITIAL_RECONNECTION_BACKOFF;
+
+        loop {
+            match self.try_connect_once().await {
+                Ok((client, jetstream)) => {
+                    {
+                        let mut shared = self.shared.write().unwrap();
+                        shared.connection = Some(client);
+                        shared.jetstream = jetstream;
+                    }
+                    let _ = self.state_tx.send(ConnectionState::Connected);
+                    self.drain_pending().await;
+                    return Ok(());
+                }
+                Err(e) => {
+                    attempt += 1;
+                    if attempt >= self.config.max_reconnection_attempts {
+                        let _ = self.state_tx.send(ConnectionState::Disconnected);
+                        // Anything buffered while we were retrying would
+                        // otherwise sit forever awaiting a reply that's
+                        // never coming, since nothing will call
+                        // `drain_pending` for this connection attempt again.
+                        self.reject_pending(|| Error::NatsConnectionNotOpen).await;
+                        return Err(e);
+                    }
+
+                    trace!(
+                        self.logger,
+                        "nats connection attempt failed, retrying";
+                        "attempt" => attempt,
+                        "backoff_ms" => backoff.as_millis() as u64,
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = std::cmp::min(backoff * 2, MAX_RECONNECTION_BACKOFF);
+                }
+            }
+        }
+    }
+
+    async fn try_connect_once(
+        &self,
+    ) -> Result<(async_nats::Client, Option<async_nats::jetstream::Context>), Error> {
+        // Disable async-nats's own background reconnection so a mid-session
+        // drop doesn't race our backoff: on `Disconnected` we clear `shared`
+        // ourselves (routing new operations back onto the buffering path)
+        // and kick off `establish_connection`, the same backoff/max-attempts
+        // loop the initial `connect()` used.
+        let client_handle = self.clone();
+        let options = async_nats::ConnectOptions::new()
+            .connection_timeout(self.config.connection_timeout)
+            .max_reconnects(Some(0))
+            .event_callback(move |event| {
+                let client_handle = client_handle.clone();
+                async move {
+                    match event {
+                        async_nats::Event::Connected => {
+                            trace!(client_handle.logger, "nats connection (re)established");
+                            let _ = client_handle.state_tx.send(ConnectionState::Connected);
+                        }
+                        async_nats::Event::Disconnected => {
+                            trace!(
+                                client_handle.logger,
+                                "nats connection dropped, reconnecting with our own backoff"
+                            );
+                            {
+                                let mut shared = client_handle.shared.write().unwrap();
+                                shared.connection.take();
+                                shared.jetstream.take();
+                            }
+                            let _ = client_handle.state_tx.send(ConnectionState::Disconnected);
+
+                            let reconnect_handle = client_handle.clone();
+                            tokio::spawn(async move {
+                                if let Err(e) = reconnect_handle.establish_connection().await {
+                                    trace!(
+                                        reconnect_handle.logger,
+                                        "gave up reconnecting after a dropped nats connection";
+                                        "err" => %e,
+                                    );
+                                }
+                            });
+                        }
+                        _ => {}
+                    }
+                }
+            });
+        let client = tokio::time::timeout(
+            self.config.connection_timeout,
+            async_nats::connect_with_options(&self.config.address, options),
+        )
+        .await
+        .map_err(|_| Error::Nats(nats_timeout_error()))?
+        .map_err(nats_io_error)?;
+
+        let jetstream = if let PushDelivery::JetStream {
+            ttl,
+            storage,
+            max_msgs_per_subject,
+        } = &self.config.push_delivery
+        {
+            let jetstream = async_nats::jetstream::new(client.clone());
+            jetstream
+                .get_or_create_stream(async_nats::jetstream::stream::Config {
+                    name: PUSH_STREAM_NAME.to_owned(),
+                    subjects: vec![PUSH_STREAM_SUBJECTS.to_owned()],
+                    storage: match storage {
+                        StorageType::File => async_nats::jetstream::stream::StorageType::File,
+                        StorageType::Memory => async_nats::jetstream::stream::StorageType::Memory,
+                    },
+                    max_age: *ttl,
+                    max_messages_per_subject: *max_msgs_per_subject,
+                    ..Default::default()
+                })
+                .await
+                .map_err(nats_io_error)?;
+            Some(jetstream)
+        } else {
+            None
+        };
+
+        Ok((client, jetstream))
+    }
+
+    async fn enqueue_pending(&self, op: PendingOp) -> Result<(), Error> {
+        let mut pending = self.pending.lock().await;
+        if pending.len() as u32 >= self.config.max_pending_messages {
+            return Err(Error::PendingBufferFull);
+        }
+        pending.push_back(op);
+        Ok(())
+    }
+
+    async fn drain_pending(&self) {
+        let ops: Vec<PendingOp> = {
+            let mut pending = self.pending.lock().await;
+            pending.drain(..).collect()
+        };
+
+        let connection = match self.connection() {
+            Some(connection) => connection,
+            None => return,
+        };
+        let jetstream = self.jetstream();
+
+        for op in ops {
+            match op {
+                PendingOp::Call { target, req, reply } => {
+                    let result = send_call(
+                        &connection,
+                        &self.logger,
+                        self.config.request_timeout,
+                        target,
+                        req,
+                    )
+                    .await;
+                    let _ = reply.send(result);
+                }
+                PendingOp::KickUser {
+                    server_kind,
+                    kick_msg,
+                    reply,
+                } => {
+                    let result = send_kick_user(
+                        &connection,
+                        &self.logger,
+                        self.config.request_timeout,
+                        &server_kind,
+                        kick_msg,
+                    )
+                    .await;
+                    let _ = reply.send(result);
+                }
+                PendingOp::Push {
+                    server_kind,
+                    push_msg,
+                    reply,
+                } => {
+                    let result = send_push(
+                        Some(&connection),
+                        jetstream.as_ref(),
+                        self.config.request_timeout,
+                        &server_kind,
+                        push_msg,
+                    )
+                    .await;
+                    let _ = reply.send(result);
+                }
+            }
+        }
+    }
+
+    /// Creates (or reuses) the durable JetStream consumer a frontend uses
+    /// to replay pushes `uid` missed while offline, in publish order.
+    /// Returns `Ok(None)` when `push_delivery` isn't `PushDelivery::JetStream`,
+    /// since there's nothing to replay from, and also while the client is
+    /// disconnected or paused, since it then holds no JetStream context.
+    pub async fn durable_user_push_consumer(
+        &self,
+        uid: &str,
+        server_kind: &ServerKind,
+    ) -> Result<Option<async_nats::jetstream::consumer::PullConsumer>, Error> {
+        let jetstream = match self.jetstream() {
+            Some(jetstream) => jetstream,
+            None => return Ok(None),
+        };
+
+        let topic = utils::user_messages_topic(uid, server_kind);
+        let durable_name = format!("pitaya-push-{}", topic.replace('.', "-"));
+
+        let stream = jetstream
+            .get_stream(PUSH_STREAM_NAME)
+            .await
+            .map_err(nats_io_error)?;
+
+        let consumer = stream
+            .get_or_create_consumer(
+                &durable_name,
+                async_nats::jetstream::consumer::pull::Config {
+                    durable_name: Some(durable_name.clone()),
+                    filter_subject: topic,
+                    ack_policy: async_nats::jetstream::consumer::AckPolicy::Explicit,
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(nats_io_error)?;
+
+        Ok(Some(consumer))
+    }
+
+    /// Replays every push buffered for `uid` since it last acked, handing
+    /// each to `on_push` in publish order and acking it immediately after.
+    /// Returns once the backlog is drained, i.e. no new message arrives
+    /// within `replay_idle_timeout`. No-op when not running in JetStream
+    /// mode, and also while the client is disconnected or paused: it then
+    /// returns `Ok(())` without replaying anything.
+    pub async fn replay_pending_pushes<F>(
+        &self,
+        uid: &str,
+        server_kind: &ServerKind,
+        mut on_push: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(protos::Push),
+    {
+        let consumer = match self.durable_user_push_consumer(uid, server_kind).await? {
+            Some(consumer) => consumer,
+            None => return Ok(()),
+        };
+
+        let mut messages = consumer.messages().await.map_err(nats_io_error)?;
+
+        loop {
+            match tokio::time::timeout(self.config.replay_idle_timeout, messages.next()).await {
+                Ok(Some(Ok(message))) => {
+                    let push: protos::Push = Message::decode(message.payload.as_ref())?;
+                    on_push(push);
+                    message.ack().await.map_err(nats_io_error)?;
+                }
+                Ok(Some(Err(e))) => return Err(nats_io_error(e)),
+                // Stream closed or backlog drained without a new message
+                // within replay_idle_timeout: caller is caught up.
+                Ok(None) | Err(_) => return Ok(()),
+            }
         }
     }
 }
@@ -84,32 +560,29 @@ impl RpcClient for NatsClient {
         req: protos::Request,
     ) -> Result<protos::Response, Error> {
         trace!(self.logger, "NatsClient::call");
-        let connection = self
-            .connection
-            .as_ref()
-            .map(|conn| conn.clone())
-            .ok_or(Error::NatsConnectionNotOpen)?;
-        let topic = utils::topic_for_server(&target);
-        let buffer = utils::encode_proto(&req);
-
-        trace!(
-            self.logger,
-            "sending nats request"; "topic" => &topic, "timeout" => self.config.request_timeout.as_secs()
-        );
-
-        let request_timeout = self.config.request_timeout.clone();
-
-        // We do a spawn_blocking here, since it otherwise will block the executor thread.
-        let response = tokio::task::spawn_blocking(move || -> Result<protos::Response, Error> {
-            let message = connection
-                .request_timeout(&topic, buffer, request_timeout)
-                .map_err(|e| Error::Nats(e))?;
-            let msg: protos::Response = Message::decode(message.data.as_ref())?;
-            Ok(msg)
-        })
-        .await??;
+        self.check_not_shutting_down()?;
+
+        let connection = match self.connection() {
+            Some(connection) => connection,
+            None => {
+                let (reply, rx) = oneshot::channel();
+                self.enqueue_pending(PendingOp::Call { target, req, reply })
+                    .await?;
+                return rx.await.map_err(|_| Error::NatsConnectionNotOpen)?;
+            }
+        };
 
-        Ok(response)
+        race_with_trip_wire(
+            self.trip_wire().as_ref(),
+            send_call(
+                &connection,
+                &self.logger,
+                self.config.request_timeout,
+                target,
+                req,
+            ),
+        )
+        .await
     }
 
     async fn kick_user(
@@ -119,11 +592,8 @@ impl RpcClient for NatsClient {
         kick_msg: protos::KickMsg,
     ) -> Result<protos::KickAnswer, Error> {
         trace!(self.logger, "NatsClient::kick_user");
-        let connection = self
-            .connection
-            .as_ref()
-            .cloned()
-            .ok_or(Error::NatsConnectionNotOpen)?;
+        self.check_not_shutting_down()?;
+
         // NOTE: Ignore server_id, since it is not necessary to create the topic.
         if kick_msg.user_id.is_empty() {
             return Err(Error::InvalidUserId);
@@ -133,26 +603,34 @@ impl RpcClient for NatsClient {
             return Err(Error::InvalidServerKind);
         }
 
-        let request_timeout = self.config.request_timeout.clone();
-        let kick_answer =
-            tokio::task::spawn_blocking(move || -> Result<protos::KickAnswer, Error> {
-                let topic = utils::user_kick_topic(&kick_msg.user_id, &server_kind);
-                let kick_buffer = utils::encode_proto(&kick_msg);
-
-                let message = connection
-                    .request_timeout(&topic, kick_buffer, request_timeout)
-                    .map_err(|e| Error::Nats(e))?;
-
-                let k: protos::KickAnswer =
-                    Message::decode(&message.data[..]).map_err(|e| Error::InvalidProto(e))?;
-                Ok(k)
-            })
-            .await??;
+        let connection = match self.connection() {
+            Some(connection) => connection,
+            None => {
+                let (reply, rx) = oneshot::channel();
+                self.enqueue_pending(PendingOp::KickUser {
+                    server_kind,
+                    kick_msg,
+                    reply,
+                })
+                .await?;
+                return rx.await.map_err(|_| Error::NatsConnectionNotOpen)?;
+            }
+        };
 
-        Ok(kick_answer)
+        race_with_trip_wire(
+            self.trip_wire().as_ref(),
+            send_kick_user(
+                &connection,
+                &self.logger,
+                self.config.request_timeout,
+                &server_kind,
+                kick_msg,
+            ),
+        )
+        .await
     }
 
-    fn push_to_user(
+    async fn push_to_user(
         &self,
         // NOTE: we ignore the server id, since it is not necessary to create the topic.
         _server_id: &ServerId,
@@ -160,10 +638,8 @@ impl RpcClient for NatsClient {
         push_msg: protos::Push,
     ) -> Result<(), Error> {
         trace!(self.logger, "NatsClient::push_to_user");
-        let connection = self
-            .connection
-            .as_ref()
-            .ok_or(Error::NatsConnectionNotOpen)?;
+        self.check_not_shutting_down()?;
+
         if push_msg.uid.is_empty() {
             return Err(Error::InvalidUserId);
         }
@@ -172,18 +648,136 @@ impl RpcClient for NatsClient {
             return Err(Error::InvalidServerKind);
         }
 
-        let topic = utils::user_messages_topic(&push_msg.uid, server_kind);
-        let push_buffer = utils::encode_proto(&push_msg);
+        let connection = self.connection();
+        let jetstream = self.jetstream();
 
-        // TODO(lhahn): should we handle the returned message here somehow?
-        let _message = connection
-            .request_timeout(&topic, push_buffer, self.config.request_timeout)
-            .map_err(|e| Error::Nats(e))?;
+        if connection.is_none() && jetstream.is_none() {
+            let (reply, rx) = oneshot::channel();
+            self.enqueue_pending(PendingOp::Push {
+                server_kind: server_kind.clone(),
+                push_msg,
+                reply,
+            })
+            .await?;
+            return rx.await.map_err(|_| Error::NatsConnectionNotOpen)?;
+        }
 
-        Ok(())
+        race_with_trip_wire(
+            self.trip_wire().as_ref(),
+            send_push(
+                connection.as_ref(),
+                jetstream.as_ref(),
+                self.config.request_timeout,
+                server_kind,
+                push_msg,
+            ),
+        )
+        .await
     }
 }
 
+async fn send_call(
+    connection: &async_nats::Client,
+    logger: &slog::Logger,
+    request_timeout: Duration,
+    target: Arc<Server>,
+    req: protos::Request,
+) -> Result<protos::Response, Error> {
+    let topic = utils::topic_for_server(&target);
+    let buffer = utils::encode_proto(&req);
+
+    trace!(
+        logger,
+        "sending nats request"; "topic" => &topic, "timeout" => request_timeout.as_secs()
+    );
+
+    let message = tokio::time::timeout(request_timeout, connection.request(topic, buffer.into()))
+        .await
+        .map_err(|_| Error::Nats(nats_timeout_error()))?
+        .map_err(nats_io_error)?;
+
+    let msg: protos::Response = Message::decode(message.payload.as_ref())?;
+
+    Ok(msg)
+}
+
+async fn send_kick_user(
+    connection: &async_nats::Client,
+    _logger: &slog::Logger,
+    request_timeout: Duration,
+    server_kind: &ServerKind,
+    kick_msg: protos::KickMsg,
+) -> Result<protos::KickAnswer, Error> {
+    let topic = utils::user_kick_topic(&kick_msg.user_id, server_kind);
+    let kick_buffer = utils::encode_proto(&kick_msg);
+
+    let message = tokio::time::timeout(
+        request_timeout,
+        connection.request(topic, kick_buffer.into()),
+    )
+    .await
+    .map_err(|_| Error::Nats(nats_timeout_error()))?
+    .map_err(nats_io_error)?;
+
+    let k: protos::KickAnswer =
+        Message::decode(message.payload.as_ref()).map_err(|e| Error::InvalidProto(e))?;
+
+    Ok(k)
+}
+
+async fn send_push(
+    connection: Option<&async_nats::Client>,
+    jetstream: Option<&async_nats::jetstream::Context>,
+    request_timeout: Duration,
+    server_kind: &ServerKind,
+    push_msg: protos::Push,
+) -> Result<(), Error> {
+    let topic = utils::user_messages_topic(&push_msg.uid, server_kind);
+    let push_buffer = utils::encode_proto(&push_msg);
+
+    if let Some(jetstream) = jetstream {
+        // Durable mode: publish into JetStream and wait for the broker to
+        // ack that the message was persisted, so a disconnected user can
+        // still replay it once it reconnects.
+        tokio::time::timeout(request_timeout, async {
+            jetstream
+                .publish(topic, push_buffer.into())
+                .await
+                .map_err(nats_io_error)?
+                .await
+                .map_err(nats_io_error)
+        })
+        .await
+        .map_err(|_| Error::Nats(nats_timeout_error()))??;
+
+        return Ok(());
+    }
+
+    let connection = connection.ok_or(Error::NatsConnectionNotOpen)?;
+
+    // TODO(lhahn): should we handle the returned message here somehow?
+    let _message = tokio::time::timeout(
+        request_timeout,
+        connection.request(topic, push_buffer.into()),
+    )
+    .await
+    .map_err(|_| Error::Nats(nats_timeout_error()))?
+    .map_err(nats_io_error)?;
+
+    Ok(())
+}
+
+pub(crate) fn nats_timeout_error() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::TimedOut, "nats request timed out")
+}
+
+fn nats_io_error<E: std::fmt::Display>(err: E) -> Error {
+    Error::Nats(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        err.to_string(),
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -205,30 +799,31 @@ mod tests {
         );
     }
 
-    #[test]
+    #[tokio::test]
     #[should_panic]
-    fn nats_fails_connection() {
-        let mut client = NatsClient::new(
+    async fn nats_fails_connection() {
+        let client = NatsClient::new(
             test_helpers::get_root_logger(),
             Arc::new(Config {
                 address: "https://nats-io.server:3241".to_owned(),
+                max_reconnection_attempts: 1,
                 ..Config::default()
             }),
         );
-        client.connect().unwrap();
+        client.connect().await.unwrap();
         client.close();
     }
 
     #[tokio::test]
     async fn nats_request_timeout() -> Result<(), Error> {
-        let mut client = NatsClient::new(
+        let client = NatsClient::new(
             test_helpers::get_root_logger(),
             Arc::new(Config {
                 request_timeout: Duration::from_millis(300),
                 ..Config::default()
             }),
         );
-        client.connect()?;
+        client.connect().await?;
 
         let target_server = Arc::new(Server {
             id: ServerId::from("my_id"),
@@ -294,14 +889,14 @@ mod tests {
 
         let mut service_discovery = start_service_disovery().await?;
 
-        let mut client = NatsClient::new(
+        let client = NatsClient::new(
             test_helpers::get_root_logger(),
             Arc::new(Config {
                 request_timeout: Duration::from_millis(300),
                 ..Config::default()
             }),
         );
-        client.connect()?;
+        client.connect().await?;
 
         let servers_by_kind = service_discovery
             .servers_by_kind(&ServerKind::from("room"))
@@ -341,4 +936,41 @@ mod tests {
         client.close();
         Ok(())
     }
+
+    #[tokio::test]
+    async fn disconnected_client_buffers_calls_and_rejects_overflow() {
+        let client = NatsClient::new(
+            test_helpers::get_root_logger(),
+            Arc::new(Config {
+                max_pending_messages: 1,
+                ..Config::default()
+            }),
+        );
+
+        assert_eq!(client.state(), ConnectionState::Disconnected);
+
+        let target_server = Arc::new(Server {
+            id: ServerId::from("my_id"),
+            kind: ServerKind::from("metagame"),
+            metadata: HashMap::new(),
+            hostname: "hostname".to_owned(),
+            frontend: false,
+        });
+
+        // The first call is buffered since there is no connection yet...
+        let client_clone = client.clone();
+        let target_clone = target_server.clone();
+        let pending_call = tokio::spawn(async move {
+            client_clone
+                .call(target_clone, protos::Request::default())
+                .await
+        });
+
+        // ... but once the buffer is full, further calls fail fast.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let overflow = client.call(target_server, protos::Request::default()).await;
+        assert!(matches!(overflow, Err(Error::PendingBufferFull)));
+
+        pending_call.abort();
+    }
 }
@@ -0,0 +1,25 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("nats error: {0}")]
+    Nats(std::io::Error),
+
+    #[error("nats connection is not open")]
+    NatsConnectionNotOpen,
+
+    #[error("invalid user id")]
+    InvalidUserId,
+
+    #[error("invalid server kind")]
+    InvalidServerKind,
+
+    #[error("invalid proto: {0}")]
+    InvalidProto(#[from] prost::DecodeError),
+
+    #[error("pending message buffer is full")]
+    PendingBufferFull,
+
+    #[error("client is shutting down")]
+    ShuttingDown,
+}
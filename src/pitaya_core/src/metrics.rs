@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use std::collections::HashMap;
 use std::sync::Arc;
 use thiserror::Error;
 use tokio::sync::RwLock;
@@ -97,6 +98,447 @@ impl Reporter for DummyReporter {
     }
 }
 
+/// A reporter that registers metrics with a `prometheus::Registry` and
+/// serves them in the text exposition format over a small embedded HTTP
+/// server, for scraping by a Prometheus server.
+pub struct PrometheusReporter {
+    logger: slog::Logger,
+    bind_addr: std::net::SocketAddr,
+    registry: prometheus::Registry,
+    counters: HashMap<String, prometheus::CounterVec>,
+    histograms: HashMap<String, prometheus::HistogramVec>,
+    gauges: HashMap<String, prometheus::GaugeVec>,
+    shutdown_tx: Option<tokio::sync::oneshot::Sender<()>>,
+    server_handle: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl PrometheusReporter {
+    pub fn new(logger: slog::Logger, bind_addr: std::net::SocketAddr) -> Self {
+        Self {
+            logger,
+            bind_addr,
+            registry: prometheus::Registry::new(),
+            counters: HashMap::new(),
+            histograms: HashMap::new(),
+            gauges: HashMap::new(),
+            shutdown_tx: None,
+            server_handle: None,
+        }
+    }
+}
+
+fn label_names(labels: &[String]) -> Vec<&str> {
+    labels.iter().map(String::as_str).collect()
+}
+
+#[async_trait]
+impl Reporter for PrometheusReporter {
+    fn register_counter(&mut self, opts: Opts) -> Result<(), Error> {
+        let prom_opts = prometheus::Opts::new(opts.name.clone(), opts.help)
+            .namespace(opts.namespace)
+            .subsystem(opts.subsystem);
+        let counter = prometheus::CounterVec::new(prom_opts, &label_names(&opts.variable_labels))
+            .map_err(|e| Error::InvalidMetric(e.to_string()))?;
+        self.registry
+            .register(Box::new(counter.clone()))
+            .map_err(|e| Error::InvalidMetric(e.to_string()))?;
+        self.counters.insert(opts.name, counter);
+        Ok(())
+    }
+
+    fn register_histogram(&mut self, opts: Opts) -> Result<(), Error> {
+        let prom_opts = prometheus::HistogramOpts::new(opts.name.clone(), opts.help)
+            .namespace(opts.namespace)
+            .subsystem(opts.subsystem)
+            .buckets(opts.buckets);
+        let histogram =
+            prometheus::HistogramVec::new(prom_opts, &label_names(&opts.variable_labels))
+                .map_err(|e| Error::InvalidMetric(e.to_string()))?;
+        self.registry
+            .register(Box::new(histogram.clone()))
+            .map_err(|e| Error::InvalidMetric(e.to_string()))?;
+        self.histograms.insert(opts.name, histogram);
+        Ok(())
+    }
+
+    fn register_gauge(&mut self, opts: Opts) -> Result<(), Error> {
+        let prom_opts = prometheus::Opts::new(opts.name.clone(), opts.help)
+            .namespace(opts.namespace)
+            .subsystem(opts.subsystem);
+        let gauge = prometheus::GaugeVec::new(prom_opts, &label_names(&opts.variable_labels))
+            .map_err(|e| Error::InvalidMetric(e.to_string()))?;
+        self.registry
+            .register(Box::new(gauge.clone()))
+            .map_err(|e| Error::InvalidMetric(e.to_string()))?;
+        self.gauges.insert(opts.name, gauge);
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        let registry = self.registry.clone();
+
+        let make_svc = hyper::service::make_service_fn(move |_conn| {
+            let registry = registry.clone();
+            async move {
+                Ok::<_, std::convert::Infallible>(hyper::service::service_fn(move |_req| {
+                    let registry = registry.clone();
+                    async move {
+                        let encoder = prometheus::TextEncoder::new();
+                        let mut buffer = Vec::new();
+                        if let Err(e) = encoder.encode(&registry.gather(), &mut buffer) {
+                            return Ok::<_, std::convert::Infallible>(
+                                hyper::Response::builder()
+                                    .status(hyper::StatusCode::INTERNAL_SERVER_ERROR)
+                                    .body(hyper::Body::from(e.to_string()))
+                                    .expect("failed to build response"),
+                            );
+                        }
+                        Ok(hyper::Response::new(hyper::Body::from(buffer)))
+                    }
+                }))
+            }
+        });
+
+        let server = hyper::Server::try_bind(&self.bind_addr)
+            .map_err(|e| Error::FailedToStartServer(e.to_string()))?
+            .serve(make_svc);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        let server = server.with_graceful_shutdown(async {
+            let _ = shutdown_rx.await;
+        });
+
+        let logger = self.logger.clone();
+        self.server_handle = Some(tokio::spawn(async move {
+            if let Err(e) = server.await {
+                slog::error!(logger, "prometheus metrics server failed"; "err" => %e);
+            }
+        }));
+        self.shutdown_tx = Some(shutdown_tx);
+
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<(), Error> {
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            let _ = shutdown_tx.send(());
+        }
+        if let Some(handle) = self.server_handle.take() {
+            let _ = handle.await;
+        }
+        Ok(())
+    }
+
+    fn inc_counter(&self, name: &str, labels: &[&str]) -> Result<(), Error> {
+        let counter = self
+            .counters
+            .get(name)
+            .ok_or_else(|| Error::InvalidMetric(name.to_owned()))?;
+        counter
+            .get_metric_with_label_values(labels)
+            .map_err(|e| Error::InvalidMetric(e.to_string()))?
+            .inc();
+        Ok(())
+    }
+
+    fn observe_hist(&self, name: &str, value: f64, labels: &[&str]) -> Result<(), Error> {
+        let histogram = self
+            .histograms
+            .get(name)
+            .ok_or_else(|| Error::InvalidMetric(name.to_owned()))?;
+        histogram
+            .get_metric_with_label_values(labels)
+            .map_err(|e| Error::InvalidMetric(e.to_string()))?
+            .observe(value);
+        Ok(())
+    }
+
+    fn set_gauge(&self, name: &str, value: f64, labels: &[&str]) -> Result<(), Error> {
+        let gauge = self
+            .gauges
+            .get(name)
+            .ok_or_else(|| Error::InvalidMetric(name.to_owned()))?;
+        gauge
+            .get_metric_with_label_values(labels)
+            .map_err(|e| Error::InvalidMetric(e.to_string()))?
+            .set(value);
+        Ok(())
+    }
+
+    fn add_gauge(&self, name: &str, value: f64, labels: &[&str]) -> Result<(), Error> {
+        let gauge = self
+            .gauges
+            .get(name)
+            .ok_or_else(|| Error::InvalidMetric(name.to_owned()))?;
+        gauge
+            .get_metric_with_label_values(labels)
+            .map_err(|e| Error::InvalidMetric(e.to_string()))?
+            .add(value);
+        Ok(())
+    }
+}
+
+/// The kind of statsd line a registered metric should be emitted as.
+#[derive(Clone)]
+struct StatsdMetric {
+    full_name: String,
+    label_names: Vec<String>,
+    kind: MetricKind,
+}
+
+/// Conservative UDP payload size that stays under the MTU of most networks,
+/// used to decide when a batch of statsd lines must be flushed.
+const STATSD_MAX_DATAGRAM_SIZE: usize = 1432;
+
+/// How often buffered metrics are flushed even if the batch never reaches
+/// `STATSD_MAX_DATAGRAM_SIZE`, so a quiet period doesn't leave the last few
+/// emitted metrics sitting in memory instead of reaching the agent.
+const STATSD_FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// The socket and batch buffer, held behind an `Arc` so the periodic flush
+/// task spawned by `start` can reach them without borrowing `StatsdReporter`.
+struct StatsdState {
+    logger: slog::Logger,
+    agent_addr: std::net::SocketAddr,
+    socket: std::sync::Mutex<Option<std::net::UdpSocket>>,
+    batch: std::sync::Mutex<Vec<String>>,
+}
+
+impl StatsdState {
+    fn emit(&self, line: String) {
+        let mut batch = self.batch.lock().expect("statsd batch lock poisoned");
+        let current_size: usize = batch.iter().map(|l| l.len() + 1).sum();
+        if !batch.is_empty() && current_size + line.len() + 1 > STATSD_MAX_DATAGRAM_SIZE {
+            self.flush_locked(&mut batch);
+        }
+        batch.push(line);
+        let new_size: usize = batch.iter().map(|l| l.len() + 1).sum();
+        if new_size >= STATSD_MAX_DATAGRAM_SIZE {
+            self.flush_locked(&mut batch);
+        }
+    }
+
+    fn flush_locked(&self, batch: &mut Vec<String>) {
+        if batch.is_empty() {
+            return;
+        }
+        let payload = batch.join("\n");
+        batch.clear();
+
+        let socket_guard = self.socket.lock().expect("statsd socket lock poisoned");
+        if let Some(socket) = socket_guard.as_ref() {
+            if let Err(e) = socket.send_to(payload.as_bytes(), self.agent_addr) {
+                if e.kind() != std::io::ErrorKind::WouldBlock {
+                    slog::warn!(self.logger, "failed to send statsd datagram"; "err" => %e);
+                }
+            }
+        }
+    }
+
+    /// Flushes any metrics buffered but not yet sent. Called on `shutdown`
+    /// and on every tick of the periodic flush task started by `start`.
+    fn flush(&self) {
+        let mut batch = self.batch.lock().expect("statsd batch lock poisoned");
+        self.flush_locked(&mut batch);
+    }
+}
+
+/// A reporter that pushes metrics to a StatsD/DogStatsD agent over UDP.
+///
+/// Unlike `PrometheusReporter`, emission methods (`inc_counter`,
+/// `observe_hist`, ...) are synchronous, so the socket is a plain
+/// non-blocking `std::net::UdpSocket`: sends never await, they just get
+/// dropped by the OS (and by the agent) if the pipe is momentarily full,
+/// which is the usual tradeoff for statsd-style metrics. A background task
+/// started by `start()` flushes the batch every `STATSD_FLUSH_INTERVAL` so
+/// metrics emitted between bursts don't go stale waiting for the batch to
+/// fill up.
+pub struct StatsdReporter {
+    state: Arc<StatsdState>,
+    metrics: HashMap<String, StatsdMetric>,
+    flush_handle: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl StatsdReporter {
+    pub fn new(logger: slog::Logger, agent_addr: std::net::SocketAddr) -> Self {
+        Self {
+            state: Arc::new(StatsdState {
+                logger,
+                agent_addr,
+                socket: std::sync::Mutex::new(None),
+                batch: std::sync::Mutex::new(Vec::new()),
+            }),
+            metrics: HashMap::new(),
+            flush_handle: None,
+        }
+    }
+
+    fn metric(&self, name: &str) -> Result<&StatsdMetric, Error> {
+        self.metrics
+            .get(name)
+            .ok_or_else(|| Error::InvalidMetric(name.to_owned()))
+    }
+
+    fn tags(metric: &StatsdMetric, labels: &[&str]) -> Result<String, Error> {
+        if metric.label_names.len() != labels.len() {
+            return Err(Error::InvalidMetric(format!(
+                "metric {} expects {} labels, got {}",
+                metric.full_name,
+                metric.label_names.len(),
+                labels.len()
+            )));
+        }
+        if labels.is_empty() {
+            return Ok(String::new());
+        }
+        let tags = metric
+            .label_names
+            .iter()
+            .zip(labels.iter())
+            .map(|(name, value)| format!("{}:{}", name, value))
+            .collect::<Vec<_>>()
+            .join(",");
+        Ok(format!("|#{}", tags))
+    }
+
+    /// Flushes any metrics buffered but not yet sent. Called on `shutdown`,
+    /// but can also be used to force a flush outside of the periodic task.
+    pub fn flush(&self) {
+        self.state.flush();
+    }
+}
+
+fn full_metric_name(opts: &Opts) -> String {
+    [
+        opts.namespace.as_str(),
+        opts.subsystem.as_str(),
+        opts.name.as_str(),
+    ]
+    .into_iter()
+    .filter(|s| !s.is_empty())
+    .collect::<Vec<_>>()
+    .join(".")
+}
+
+#[async_trait]
+impl Reporter for StatsdReporter {
+    fn register_counter(&mut self, opts: Opts) -> Result<(), Error> {
+        let metric = StatsdMetric {
+            full_name: full_metric_name(&opts),
+            label_names: opts.variable_labels,
+            kind: MetricKind::Counter,
+        };
+        self.metrics.insert(opts.name, metric);
+        Ok(())
+    }
+
+    fn register_histogram(&mut self, opts: Opts) -> Result<(), Error> {
+        let metric = StatsdMetric {
+            full_name: full_metric_name(&opts),
+            label_names: opts.variable_labels,
+            kind: MetricKind::Histogram,
+        };
+        self.metrics.insert(opts.name, metric);
+        Ok(())
+    }
+
+    fn register_gauge(&mut self, opts: Opts) -> Result<(), Error> {
+        let metric = StatsdMetric {
+            full_name: full_metric_name(&opts),
+            label_names: opts.variable_labels,
+            kind: MetricKind::Gauge,
+        };
+        self.metrics.insert(opts.name, metric);
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        let socket = std::net::UdpSocket::bind("0.0.0.0:0")
+            .map_err(|e| Error::FailedToStartServer(e.to_string()))?;
+        socket
+            .set_nonblocking(true)
+            .map_err(|e| Error::FailedToStartServer(e.to_string()))?;
+        *self
+            .state
+            .socket
+            .lock()
+            .expect("statsd socket lock poisoned") = Some(socket);
+
+        let state = self.state.clone();
+        self.flush_handle = Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(STATSD_FLUSH_INTERVAL);
+            interval.tick().await; // first tick fires immediately
+            loop {
+                interval.tick().await;
+                state.flush();
+            }
+        }));
+
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<(), Error> {
+        if let Some(handle) = self.flush_handle.take() {
+            handle.abort();
+        }
+        self.flush();
+        self.state
+            .socket
+            .lock()
+            .expect("statsd socket lock poisoned")
+            .take();
+        Ok(())
+    }
+
+    fn inc_counter(&self, name: &str, labels: &[&str]) -> Result<(), Error> {
+        let metric = self.metric(name)?;
+        if metric.kind != MetricKind::Counter {
+            return Err(Error::InvalidMetric(format!("{} is not a counter", name)));
+        }
+        let tags = Self::tags(metric, labels)?;
+        self.state.emit(format!("{}:1|c{}", metric.full_name, tags));
+        Ok(())
+    }
+
+    fn observe_hist(&self, name: &str, value: f64, labels: &[&str]) -> Result<(), Error> {
+        let metric = self.metric(name)?;
+        if metric.kind != MetricKind::Histogram {
+            return Err(Error::InvalidMetric(format!("{} is not a histogram", name)));
+        }
+        let tags = Self::tags(metric, labels)?;
+        self.state
+            .emit(format!("{}:{}|h{}", metric.full_name, value, tags));
+        Ok(())
+    }
+
+    fn set_gauge(&self, name: &str, value: f64, labels: &[&str]) -> Result<(), Error> {
+        let metric = self.metric(name)?;
+        if metric.kind != MetricKind::Gauge {
+            return Err(Error::InvalidMetric(format!("{} is not a gauge", name)));
+        }
+        let tags = Self::tags(metric, labels)?;
+        self.state
+            .emit(format!("{}:{}|g{}", metric.full_name, value, tags));
+        Ok(())
+    }
+
+    fn add_gauge(&self, name: &str, value: f64, labels: &[&str]) -> Result<(), Error> {
+        let metric = self.metric(name)?;
+        if metric.kind != MetricKind::Gauge {
+            return Err(Error::InvalidMetric(format!("{} is not a gauge", name)));
+        }
+        let tags = Self::tags(metric, labels)?;
+        let signed = if value >= 0.0 {
+            format!("+{}", value)
+        } else {
+            format!("{}", value)
+        };
+        self.state
+            .emit(format!("{}:{}|g{}", metric.full_name, signed, tags));
+        Ok(())
+    }
+}
+
 /// Creates buckets that are incremented exponentially.
 ///
 /// # Examples
@@ -161,3 +603,117 @@ pub async fn add_to_gauge<'a>(
         slog::warn!(logger, "add_gauge failed"; "err" => %e);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_logger() -> slog::Logger {
+        slog::Logger::root(slog::Discard, slog::o!())
+    }
+
+    fn counter_opts(name: &str, variable_labels: Vec<String>) -> Opts {
+        Opts {
+            kind: MetricKind::Counter,
+            namespace: "pitaya".to_owned(),
+            subsystem: "test".to_owned(),
+            name: name.to_owned(),
+            help: "a test counter".to_owned(),
+            variable_labels,
+            buckets: vec![],
+        }
+    }
+
+    #[test]
+    fn prometheus_unknown_metric_is_an_error() {
+        let reporter = PrometheusReporter::new(test_logger(), "127.0.0.1:0".parse().unwrap());
+        assert!(matches!(
+            reporter.inc_counter("does_not_exist", &[]),
+            Err(Error::InvalidMetric(_))
+        ));
+    }
+
+    #[test]
+    fn prometheus_label_count_mismatch_is_an_error() {
+        let mut reporter = PrometheusReporter::new(test_logger(), "127.0.0.1:0".parse().unwrap());
+        reporter
+            .register_counter(counter_opts("requests", vec!["route".to_owned()]))
+            .expect("register_counter");
+
+        assert!(matches!(
+            reporter.inc_counter("requests", &["/foo", "extra"]),
+            Err(Error::InvalidMetric(_))
+        ));
+        assert!(reporter.inc_counter("requests", &["/foo"]).is_ok());
+    }
+
+    #[test]
+    fn statsd_unknown_metric_is_an_error() {
+        let reporter = StatsdReporter::new(test_logger(), "127.0.0.1:8125".parse().unwrap());
+        assert!(matches!(
+            reporter.inc_counter("does_not_exist", &[]),
+            Err(Error::InvalidMetric(_))
+        ));
+    }
+
+    #[test]
+    fn statsd_label_count_mismatch_is_an_error() {
+        let mut reporter = StatsdReporter::new(test_logger(), "127.0.0.1:8125".parse().unwrap());
+        reporter
+            .register_counter(counter_opts("requests", vec!["route".to_owned()]))
+            .expect("register_counter");
+
+        assert!(matches!(
+            reporter.inc_counter("requests", &["/foo", "extra"]),
+            Err(Error::InvalidMetric(_))
+        ));
+    }
+
+    #[test]
+    fn statsd_emit_wrong_kind_is_an_error() {
+        let mut reporter = StatsdReporter::new(test_logger(), "127.0.0.1:8125".parse().unwrap());
+        reporter
+            .register_counter(counter_opts("requests", vec![]))
+            .expect("register_counter");
+
+        assert!(matches!(
+            reporter.set_gauge("requests", 1.0, &[]),
+            Err(Error::InvalidMetric(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn statsd_batches_until_datagram_size_then_flushes() {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").expect("bind receiver");
+        socket
+            .set_read_timeout(Some(std::time::Duration::from_secs(1)))
+            .expect("set_read_timeout");
+        let agent_addr = socket.local_addr().expect("local_addr");
+
+        let mut reporter = StatsdReporter::new(test_logger(), agent_addr);
+        reporter
+            .register_counter(counter_opts("requests", vec![]))
+            .expect("register_counter");
+        reporter.start().await.expect("start");
+
+        // A single small metric doesn't fill the batch, so it isn't flushed
+        // on size. The periodic flush task can't run meanwhile either: the
+        // blocking `recv` below holds this current-thread test runtime.
+        reporter.inc_counter("requests", &[]).expect("inc_counter");
+        let mut buf = [0u8; STATSD_MAX_DATAGRAM_SIZE * 2];
+        assert!(matches!(
+            socket.recv(&mut buf),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock
+                || e.kind() == std::io::ErrorKind::TimedOut
+        ));
+
+        // Filling the batch past STATSD_MAX_DATAGRAM_SIZE forces a flush.
+        for _ in 0..(STATSD_MAX_DATAGRAM_SIZE / "requests:1|c".len() + 1) {
+            reporter.inc_counter("requests", &[]).expect("inc_counter");
+        }
+        let n = socket.recv(&mut buf).expect("recv flushed datagram");
+        assert!(n > 0);
+
+        reporter.shutdown().await.expect("shutdown");
+    }
+}
@@ -0,0 +1,227 @@
+use crate::error::Error;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// A cloneable, `watch`-backed token that resolves once it has been
+/// tripped. `NatsClient` holds two of these (see `with_draining` and
+/// `with_trip_wire`): one tripped immediately to reject new work, and one
+/// tripped after a grace period to force-cancel whatever's still in
+/// flight.
+#[derive(Clone)]
+pub struct TripWire {
+    rx: watch::Receiver<bool>,
+}
+
+impl TripWire {
+    /// Resolves once the wire has been tripped. A no-op if it already has.
+    pub async fn tripped(&mut self) {
+        if *self.rx.borrow() {
+            return;
+        }
+        if self.rx.changed().await.is_err() {
+            // The signal was dropped without tripping, so it never will.
+            std::future::pending::<()>().await;
+        }
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        *self.rx.borrow()
+    }
+}
+
+/// The trip end of a `TripWire`. Owned by whoever is allowed to request
+/// shutdown (typically a `Shutdown` coordinator).
+pub struct TripWireSignal {
+    tx: watch::Sender<bool>,
+}
+
+impl TripWireSignal {
+    pub fn new() -> (Self, TripWire) {
+        let (tx, rx) = watch::channel(false);
+        (Self { tx }, TripWire { rx })
+    }
+
+    pub fn trip(&self) {
+        let _ = self.tx.send(true);
+    }
+}
+
+pub(crate) async fn race_with_trip_wire<T>(
+    trip_wire: Option<&TripWire>,
+    fut: impl std::future::Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    match trip_wire {
+        None => fut.await,
+        Some(trip_wire) => {
+            let mut trip_wire = trip_wire.clone();
+            tokio::select! {
+                result = fut => result,
+                _ = trip_wire.tripped() => Err(Error::ShuttingDown),
+            }
+        }
+    }
+}
+
+/// Fans a shutdown signal out to the NATS client and every metrics
+/// reporter.
+///
+/// Shutdown happens in two steps so that in-flight RPCs actually get their
+/// grace period instead of being cut off the instant shutdown starts:
+/// first the "draining" wire trips, which makes `NatsClient` reject any
+/// *new* call with `Error::ShuttingDown` right away; only after sleeping
+/// out `grace_period` does the "force" wire trip, which cancels whatever
+/// calls are still in flight via `race_with_trip_wire`.
+pub struct Shutdown {
+    draining: TripWireSignal,
+    force: TripWireSignal,
+    grace_period: Duration,
+    logger: slog::Logger,
+}
+
+impl Shutdown {
+    /// Returns the coordinator along with the draining and force
+    /// `TripWire`s to pass into `NatsClient::with_draining` and
+    /// `NatsClient::with_trip_wire` respectively.
+    pub fn new(logger: slog::Logger, grace_period: Duration) -> (Self, TripWire, TripWire) {
+        let (draining, draining_wire) = TripWireSignal::new();
+        let (force, force_wire) = TripWireSignal::new();
+        (
+            Self {
+                draining,
+                force,
+                grace_period,
+                logger,
+            },
+            draining_wire,
+            force_wire,
+        )
+    }
+
+    pub async fn shutdown(
+        &self,
+        nats_client: &crate::cluster::rpc_client::NatsClient,
+        reporters: &[crate::metrics::ThreadSafeReporter],
+    ) {
+        self.draining.trip();
+
+        tokio::time::sleep(self.grace_period).await;
+
+        self.force.trip();
+
+        nats_client.drain_and_close().await;
+
+        for reporter in reporters {
+            if let Err(e) = reporter.write().await.shutdown().await {
+                slog::warn!(self.logger, "reporter shutdown failed"; "err" => %e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn trip_wire_tripped_resolves_immediately_once_already_tripped() {
+        let (signal, mut wire) = TripWireSignal::new();
+        assert!(!wire.is_tripped());
+
+        signal.trip();
+
+        wire.tripped().await;
+        assert!(wire.is_tripped());
+    }
+
+    #[tokio::test]
+    async fn race_with_trip_wire_lets_an_in_flight_future_finish_while_untripped() {
+        let (_signal, wire) = TripWireSignal::new();
+
+        let result = race_with_trip_wire(Some(&wire), async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok::<_, Error>(42)
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn race_with_trip_wire_cancels_an_in_flight_future_once_tripped() {
+        let (signal, wire) = TripWireSignal::new();
+
+        let call = race_with_trip_wire(Some(&wire), async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok::<_, Error>(42)
+        });
+        tokio::pin!(call);
+
+        // Give the future a chance to actually be polled and start waiting
+        // before we trip the wire out from under it.
+        tokio::select! {
+            _ = &mut call => panic!("should still be in flight: the wire hasn't tripped yet"),
+            _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+        }
+
+        signal.trip();
+
+        assert!(matches!(call.await, Err(Error::ShuttingDown)));
+    }
+
+    #[tokio::test]
+    async fn shutdown_lets_in_flight_calls_finish_within_the_grace_period() {
+        let (shutdown, _draining, force) = Shutdown::new(
+            slog::Logger::root(slog::Discard, slog::o!()),
+            Duration::from_millis(200),
+        );
+
+        // Mirrors what NatsClient::call does internally: race the force
+        // wire, which Shutdown::shutdown is only supposed to trip after the
+        // grace period.
+        let in_flight = tokio::spawn(async move {
+            race_with_trip_wire(Some(&force), async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok::<_, Error>(())
+            })
+            .await
+        });
+
+        shutdown
+            .shutdown(&nats_client_without_connection(), &[])
+            .await;
+
+        assert!(matches!(in_flight.await.unwrap(), Ok(())));
+    }
+
+    #[tokio::test]
+    async fn shutdown_force_cancels_calls_still_in_flight_after_the_grace_period() {
+        let (shutdown, _draining, force) = Shutdown::new(
+            slog::Logger::root(slog::Discard, slog::o!()),
+            Duration::from_millis(20),
+        );
+
+        let in_flight = tokio::spawn(async move {
+            race_with_trip_wire(Some(&force), async {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok::<_, Error>(())
+            })
+            .await
+        });
+
+        shutdown
+            .shutdown(&nats_client_without_connection(), &[])
+            .await;
+
+        assert!(matches!(in_flight.await.unwrap(), Err(Error::ShuttingDown)));
+    }
+
+    /// A `NatsClient` that was never `connect`ed, just enough for
+    /// `Shutdown::shutdown` to call `drain_and_close` on.
+    fn nats_client_without_connection() -> crate::cluster::rpc_client::NatsClient {
+        crate::cluster::rpc_client::NatsClient::new(
+            slog::Logger::root(slog::Discard, slog::o!()),
+            Arc::new(crate::cluster::rpc_client::Config::default()),
+        )
+    }
+}